@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+/// Markdown rendering options, configurable via the `[markdown]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkdownConfig {
+    #[serde(default)]
+    pub highlight_code: bool,
+    #[serde(default = "default_highlight_theme")]
+    pub highlight_theme: String,
+    #[serde(default)]
+    pub smart_punctuation: bool,
+    #[serde(default)]
+    pub render_emoji: bool,
+    #[serde(default)]
+    pub external_links_target_blank: bool,
+    #[serde(default)]
+    pub external_links_no_follow: bool,
+    #[serde(default)]
+    pub external_links_no_referrer: bool,
+}
+
+fn default_highlight_theme() -> String {
+    "InspiredGitHub".to_string()
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig {
+            highlight_code: false,
+            highlight_theme: default_highlight_theme(),
+            smart_punctuation: false,
+            render_emoji: false,
+            external_links_target_blank: false,
+            external_links_no_follow: false,
+            external_links_no_referrer: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteConfig {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub base_url: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    /// Language codes the site is written in, used to recognise `name.LANG.md`
+    /// content filenames.
+    #[serde(default = "default_languages")]
+    pub languages: Vec<String>,
+    #[serde(default = "default_language")]
+    pub default_language: String,
+    /// Minifies rendered HTML before it's written to disk. Off by default so
+    /// dev builds stay fast and debuggable.
+    #[serde(default)]
+    pub minify_html: bool,
+}
+
+fn default_languages() -> Vec<String> {
+    vec![default_language()]
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
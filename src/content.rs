@@ -1,11 +1,335 @@
 use anyhow::Result;
 use gray_matter::{Matter, Pod};
 use gray_matter::engine::YAML;
-use pulldown_cmark::{html, Options, Parser};
+use once_cell::sync::Lazy;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::SyntaxSet;
+
+use crate::config::SiteConfig;
+use crate::templates::TemplateEngine;
+
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+static EMOJI_SHORTCODE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r":([a-zA-Z0-9_+-]+):").unwrap());
+
+/// Replaces `:shortcode:` tokens (e.g. `:tada:`) with their Unicode emoji,
+/// leaving unrecognised shortcodes untouched.
+fn render_emoji_text(text: &str) -> String {
+    EMOJI_SHORTCODE_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            emojis::get_by_shortcode(&caps[1])
+                .map(|e| e.as_str().to_string())
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Returns the hostname of a URL (the part between `://` and the next `/`,
+/// `?` or `#`).
+fn url_host(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    Some(rest.split(['/', '?', '#']).next().unwrap_or(rest))
+}
+
+/// An external link is an absolute `http(s)://` URL whose host doesn't match
+/// the site's own host.
+fn is_external_link(dest: &str, site_url: &str) -> bool {
+    if !dest.starts_with("http://") && !dest.starts_with("https://") {
+        return false;
+    }
+    match (url_host(dest), url_host(site_url)) {
+        (Some(dest_host), Some(site_host)) => dest_host != site_host,
+        _ => true,
+    }
+}
+
+/// Renders a `<a>` opening tag for an external link, adding `target="_blank"`
+/// and/or a merged `rel="..."` per the enabled config flags.
+fn render_external_link_tag(dest: &str, title: &str, config: &SiteConfig) -> String {
+    let md = &config.markdown;
+    let mut attrs = format!(
+        r#"href="{}""#,
+        html_escape::encode_double_quoted_attribute(dest)
+    );
+    if !title.is_empty() {
+        attrs.push_str(&format!(
+            r#" title="{}""#,
+            html_escape::encode_double_quoted_attribute(title)
+        ));
+    }
+    if md.external_links_target_blank {
+        attrs.push_str(r#" target="_blank""#);
+    }
+
+    let mut rel = Vec::new();
+    if md.external_links_no_follow {
+        rel.push("nofollow");
+    }
+    if md.external_links_no_referrer {
+        rel.push("noreferrer");
+    }
+    if !rel.is_empty() {
+        attrs.push_str(&format!(r#" rel="{}""#, rel.join(" ")));
+    }
+
+    format!("<a {}>", attrs)
+}
+
+/// Highlights a fenced code block's source with `syntect`, returning the
+/// finished `<pre><code>` HTML.
+fn highlight_fenced_code(lang: &str, code: &str, theme_name: &str) -> String {
+    let syntax = SYNTAX_SET
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let theme = THEME_SET
+        .themes
+        .get(theme_name)
+        .unwrap_or(&THEME_SET.themes["InspiredGitHub"]);
+
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut body = String::new();
+    for line in code.lines() {
+        let ranges = highlighter
+            .highlight_line(line, &SYNTAX_SET)
+            .unwrap_or_default();
+        if let Ok(highlighted) = styled_line_to_highlighted_html(&ranges, IncludeBackground::No) {
+            body.push_str(&highlighted);
+        }
+        body.push('\n');
+    }
+
+    format!(
+        r#"<pre><code class="language-{lang}">{body}</code></pre>"#,
+        lang = html_escape::encode_text(lang),
+        body = body
+    )
+}
+
+/// A single entry in a document's table of contents, nested by heading level.
+#[derive(Debug, Clone, Serialize)]
+pub struct TocEntry {
+    pub level: u32,
+    pub id: String,
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// Builds a nested `TocEntry` tree from a flat stream of headings, keeping
+/// track of the open ancestor chain so a higher level number is nested under
+/// the nearest preceding lower-level entry.
+#[derive(Default)]
+struct TocBuilder {
+    root: Vec<TocEntry>,
+    stack: Vec<(u32, Vec<usize>)>,
+}
+
+impl TocBuilder {
+    fn push(&mut self, level: u32, id: String, title: String) {
+        while matches!(self.stack.last(), Some((top_level, _)) if *top_level >= level) {
+            self.stack.pop();
+        }
+
+        let entry = TocEntry {
+            level,
+            id,
+            title,
+            children: Vec::new(),
+        };
+
+        let path = match self.stack.last() {
+            Some((_, parent_path)) => {
+                let parent = Self::entry_at_mut(&mut self.root, parent_path);
+                parent.children.push(entry);
+                let mut path = parent_path.clone();
+                path.push(parent.children.len() - 1);
+                path
+            }
+            None => {
+                self.root.push(entry);
+                vec![self.root.len() - 1]
+            }
+        };
+
+        self.stack.push((level, path));
+    }
+
+    fn entry_at_mut<'a>(root: &'a mut [TocEntry], path: &[usize]) -> &'a mut TocEntry {
+        let mut node = &mut root[path[0]];
+        for &i in &path[1..] {
+            node = &mut node.children[i];
+        }
+        node
+    }
+
+    fn finish(self) -> Vec<TocEntry> {
+        self.root
+    }
+}
+
+fn heading_level_to_u32(level: HeadingLevel) -> u32 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Slugifies heading text: lowercase, spaces become `-`, everything else
+/// that isn't alphanumeric or `-` is dropped.
+fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c == ' ' { '-' } else { c })
+        .filter(|c| c.is_alphanumeric() || *c == '-')
+        .collect()
+}
+
+/// Makes `slug` unique within a document by appending `-1`, `-2`, etc. until
+/// it no longer collides with an already-emitted slug, per `used`. Tracking
+/// final (post-disambiguation) slugs rather than raw pre-disambiguation text
+/// keeps an organically-repeated heading from colliding with a later literal
+/// heading whose text happens to match the auto-suffixed id.
+fn unique_slug(slug: String, used: &mut HashSet<String>) -> String {
+    if used.insert(slug.clone()) {
+        return slug;
+    }
+
+    let mut n = 1;
+    loop {
+        let candidate = format!("{}-{}", slug, n);
+        if used.insert(candidate.clone()) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Converts markdown to HTML, intercepting fenced code blocks so they can be
+/// syntax-highlighted (or at least tagged with a `language-*` class) instead
+/// of falling through to `pulldown_cmark`'s plain `<pre><code>` output, and
+/// intercepting headings to assign slugified anchor ids and build a table of
+/// contents.
+fn render_markdown(source: &str, options: Options, config: &SiteConfig) -> (String, Vec<TocEntry>) {
+    let markdown = &config.markdown;
+    let parser = Parser::new_ext(source, options);
+    let mut html_output = String::new();
+
+    let mut in_code_block = false;
+    let mut code_lang = String::new();
+    let mut code_buf = String::new();
+
+    let mut in_heading = false;
+    let mut heading_level = 1u32;
+    let mut heading_text = String::new();
+    let mut heading_events: Vec<Event> = Vec::new();
+
+    let mut used_slugs: HashSet<String> = HashSet::new();
+    let mut toc = TocBuilder::default();
+
+    for event in parser {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                in_heading = true;
+                heading_level = heading_level_to_u32(level);
+                heading_text.clear();
+                heading_events.clear();
+            }
+            Event::End(Tag::Heading(_, _, _)) => {
+                in_heading = false;
+                let slug = unique_slug(slugify(&heading_text), &mut used_slugs);
+
+                let mut inner_html = String::new();
+                html::push_html(&mut inner_html, heading_events.drain(..));
+
+                html_output.push_str(&format!(
+                    "<h{level} id=\"{id}\"><a href=\"#{id}\">{inner}</a></h{level}>\n",
+                    level = heading_level,
+                    id = slug,
+                    inner = inner_html
+                ));
+
+                toc.push(heading_level, slug, heading_text.trim().to_string());
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
+                in_code_block = true;
+                code_lang = lang.to_string();
+                code_buf.clear();
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => {
+                in_code_block = true;
+                code_lang.clear();
+                code_buf.clear();
+            }
+            Event::Start(Tag::Link(link_type, dest, title))
+                if is_external_link(&dest, &config.url)
+                    && (markdown.external_links_target_blank
+                        || markdown.external_links_no_follow
+                        || markdown.external_links_no_referrer) =>
+            {
+                let tag = render_external_link_tag(&dest, &title, config);
+                if in_heading {
+                    heading_events.push(Event::Html(tag.into()));
+                } else {
+                    html_output.push_str(&tag);
+                }
+                let _ = link_type;
+            }
+            Event::Text(text) if in_code_block => {
+                code_buf.push_str(&text);
+            }
+            Event::Text(text) if in_heading => {
+                let text: CowStr = if markdown.render_emoji {
+                    render_emoji_text(&text).into()
+                } else {
+                    text
+                };
+                heading_text.push_str(&text);
+                heading_events.push(Event::Text(text));
+            }
+            Event::Code(code) if in_heading => {
+                heading_text.push_str(&code);
+                heading_events.push(Event::Code(code));
+            }
+            Event::Text(text) => {
+                let text: CowStr = if markdown.render_emoji {
+                    render_emoji_text(&text).into()
+                } else {
+                    text
+                };
+                html::push_html(&mut html_output, std::iter::once(Event::Text(text)));
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                let block_html = if markdown.highlight_code {
+                    highlight_fenced_code(&code_lang, &code_buf, &markdown.highlight_theme)
+                } else {
+                    format!(
+                        r#"<pre><code class="language-{lang}">{code}</code></pre>"#,
+                        lang = html_escape::encode_text(&code_lang),
+                        code = html_escape::encode_text(&code_buf)
+                    )
+                };
+                html_output.push_str(&block_html);
+            }
+            other if in_heading => heading_events.push(other),
+            other => html::push_html(&mut html_output, std::iter::once(other)),
+        }
+    }
+
+    (html_output, toc.finish())
+}
 
 fn pod_to_yaml_value(pod: Pod) -> Value {
     match pod {
@@ -43,10 +367,19 @@ pub struct ContentFile {
     pub html_content: String,
     pub collection: Option<String>,
     pub language: String,
+    /// Filename stem with any `.LANG` suffix stripped, e.g. `about.fr.md`
+    /// and `about.md` both have base stem `about`.
+    pub base_stem: String,
+    pub toc: Vec<TocEntry>,
 }
 
 impl ContentFile {
-    pub fn from_path(path: &Path, source_root: &Path) -> Result<Self> {
+    pub fn from_path(
+        path: &Path,
+        source_root: &Path,
+        config: &SiteConfig,
+        templates: &TemplateEngine,
+    ) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
         let matter = Matter::<YAML>::new();
         let result = matter.parse(&content);
@@ -96,73 +429,147 @@ impl ContentFile {
         options.insert(Options::ENABLE_FOOTNOTES);
         options.insert(Options::ENABLE_TABLES);
         options.insert(Options::ENABLE_TASKLISTS);
+        if config.markdown.smart_punctuation {
+            options.insert(Options::ENABLE_SMART_PUNCTUATION);
+        }
 
-        let parser = Parser::new_ext(&result.content, options);
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
+        // Expand shortcodes before markdown conversion; `expand_shortcodes`
+        // leaves shortcode-like text inside fenced or indented code alone.
+        let expanded_content = templates.expand_shortcodes(&result.content)?;
+        let (html_output, toc) = render_markdown(&expanded_content, options, config);
 
-        // Determine collection and language from path
+        // Determine collection, base stem and language from path
         let relative_path = path.strip_prefix(source_root)?.to_path_buf();
-        let (collection, language) = Self::extract_collection_and_language(&relative_path);
+        let collection = Self::extract_collection(&relative_path);
+        let (base_stem, language) = Self::parse_stem_and_language(path, config);
 
         Ok(ContentFile {
             path: path.to_path_buf(),
             relative_path,
             front_matter,
-            content: result.content,
+            content: expanded_content,
             html_content: html_output,
             collection,
             language,
+            base_stem,
+            toc,
         })
     }
 
-    fn extract_collection_and_language(path: &Path) -> (Option<String>, String) {
+    fn extract_collection(path: &Path) -> Option<String> {
         let path_str = path.to_string_lossy();
-        
+
         if path_str.starts_with("_pages") {
-            (Some("pages".to_string()), "en".to_string())
+            Some("pages".to_string())
         } else {
-            (None, "en".to_string())
+            None
         }
     }
 
-    pub fn get_output_path(&self, _base_url: &str) -> String {
-        let stem = self.path.file_stem()
+    /// Splits a `name.LANG.md` filename into its base stem and language code.
+    /// `LANG` must be one of the configured `languages` to be recognised;
+    /// otherwise (including plain `name.md` files) the configured
+    /// `default_language` is used.
+    fn parse_stem_and_language(path: &Path, config: &SiteConfig) -> (String, String) {
+        let stem = path.file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("index");
 
-        format!("/{}/", stem)
+        if let Some((base, lang)) = stem.rsplit_once('.') {
+            if config.languages.iter().any(|l| l == lang) {
+                return (base.to_string(), lang.to_string());
+            }
+        }
+
+        (stem.to_string(), config.default_language.clone())
     }
 
-    pub fn get_file_path(&self) -> PathBuf {
-        let mut path = PathBuf::new();
-        
-        let stem = self.path.file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("index");
-        
-        if stem == "index" {
-            path.push("index.html");
+    /// Builds this file's site-relative URL (e.g. `/fr/about/`), prefixing
+    /// non-default languages with a `/LANG/` segment.
+    fn url_path(&self, default_language: &str) -> String {
+        let mut segments = Vec::new();
+
+        if self.language != default_language {
+            segments.push(self.language.as_str());
+        }
+        if self.base_stem != "index" {
+            segments.push(self.base_stem.as_str());
+        }
+
+        if segments.is_empty() {
+            "/".to_string()
         } else {
-            path.push(stem);
-            path.push("index.html");
+            format!("/{}/", segments.join("/"))
+        }
+    }
+
+    pub fn get_output_path(&self, default_language: &str) -> String {
+        self.url_path(default_language)
+    }
+
+    pub fn get_file_path(&self, default_language: &str) -> PathBuf {
+        let mut path = PathBuf::new();
+
+        if self.language != default_language {
+            path.push(&self.language);
+        }
+        if self.base_stem != "index" {
+            path.push(&self.base_stem);
         }
-        
+        path.push("index.html");
+
         path
     }
 
-    pub fn get_language_urls(&self) -> std::collections::HashMap<String, String> {
-        let mut urls = std::collections::HashMap::new();
-        let stem = self.path.file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("index");
+    /// Returns the URL for every sibling-language version of this file
+    /// (including itself), keyed by language code, so templates can render a
+    /// language switcher. Siblings must share both `base_stem` and
+    /// `collection`, so unrelated files in different collections that
+    /// happen to share a filename stem aren't wired together.
+    pub fn get_language_urls(
+        &self,
+        all_content: &[ContentFile],
+        default_language: &str,
+    ) -> HashMap<String, String> {
+        let mut urls = HashMap::new();
 
-        if stem == "index" {
-            urls.insert("en".to_string(), "/".to_string());
-        } else {
-            urls.insert("en".to_string(), format!("/{}/", stem));
+        for other in all_content {
+            if other.base_stem == self.base_stem && other.collection == self.collection {
+                urls.insert(other.language.clone(), other.url_path(default_language));
+            }
         }
-        
+
         urls
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_slug_passes_through_first_occurrence() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_slug("foo".to_string(), &mut used), "foo");
+    }
+
+    #[test]
+    fn unique_slug_suffixes_repeated_headings() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_slug("foo".to_string(), &mut used), "foo");
+        assert_eq!(unique_slug("foo".to_string(), &mut used), "foo-1");
+        assert_eq!(unique_slug("foo".to_string(), &mut used), "foo-2");
+    }
+
+    #[test]
+    fn unique_slug_does_not_collide_with_a_later_literal_match() {
+        // Two "Foo" headings auto-suffix the second to "foo-1". A later
+        // heading literally titled "Foo-1" must not collide with it.
+        let mut used = HashSet::new();
+        assert_eq!(unique_slug("foo".to_string(), &mut used), "foo");
+        assert_eq!(unique_slug("foo".to_string(), &mut used), "foo-1");
+        let literal = unique_slug("foo-1".to_string(), &mut used);
+        assert_ne!(literal, "foo-1");
+        assert_eq!(literal, "foo-1-1");
+    }
 }
\ No newline at end of file
@@ -1,25 +1,539 @@
 use anyhow::Result;
+use image::imageops::FilterType;
+use image::GenericImageView;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde_json::Value;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::path::Path;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tera::{Context, Tera};
 
 use crate::content::ContentFile;
 
+/// Matches both shortcode forms: inline `{{ name(arg="val") }}` and block
+/// `{% name(arg="val") %}...body...{% end %}`.
+static SHORTCODE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?s)\{%\s*(?P<bname>[A-Za-z_][A-Za-z0-9_]*)\((?P<bargs>[^)]*)\)\s*%\}(?P<bbody>.*?)\{%\s*end\s*%\}|\{\{\s*(?P<iname>[A-Za-z_][A-Za-z0-9_]*)\((?P<iargs>[^)]*)\)\s*\}\}"#,
+    )
+    .unwrap()
+});
+
+/// Splits a shortcode argument list on top-level commas, leaving commas
+/// inside quoted strings alone.
+fn split_shortcode_args(args: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for ch in args.chars() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            ',' if !in_quotes => {
+                parts.push(current.trim().to_string());
+                current.clear();
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+
+    parts
+}
+
+/// Parses `arg="val", n=3` into key/value pairs, guessing string/number/bool
+/// types from the literal.
+fn parse_shortcode_args(args: &str) -> Vec<(String, Value)> {
+    split_shortcode_args(args)
+        .into_iter()
+        .filter_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            let key = key.trim().to_string();
+            let value = value.trim();
+
+            let parsed = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                Value::String(value[1..value.len() - 1].to_string())
+            } else if let Ok(n) = value.parse::<i64>() {
+                Value::Number(n.into())
+            } else if let Ok(f) = value.parse::<f64>() {
+                serde_json::json!(f)
+            } else if value == "true" || value == "false" {
+                Value::Bool(value == "true")
+            } else {
+                Value::String(value.to_string())
+            };
+
+            Some((key, parsed))
+        })
+        .collect()
+}
+
+fn tera_required_str<'a>(args: &'a HashMap<String, Value>, name: &str) -> tera::Result<&'a str> {
+    args.get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| tera::Error::msg(format!("missing `{}` argument", name)))
+}
+
+fn tera_required_u32(args: &HashMap<String, Value>, name: &str) -> tera::Result<u32> {
+    args.get(name)
+        .and_then(|v| v.as_u64())
+        .map(|n| n as u32)
+        .ok_or_else(|| tera::Error::msg(format!("resize_image: missing `{}` argument", name)))
+}
+
+/// Computes the target height that preserves `orig_w`x`orig_h`'s aspect ratio
+/// when scaling to `width`, clamped to at least 1px.
+fn fit_width_height(orig_w: u32, orig_h: u32, width: u32) -> u32 {
+    let target_h = ((orig_h as f64) * (width as f64) / (orig_w as f64)).round() as u32;
+    target_h.max(1)
+}
+
+/// Computes the target width that preserves `orig_w`x`orig_h`'s aspect ratio
+/// when scaling to `height`, clamped to at least 1px.
+fn fit_height_width(orig_w: u32, orig_h: u32, height: u32) -> u32 {
+    let target_w = ((orig_w as f64) * (height as f64) / (orig_h as f64)).round() as u32;
+    target_w.max(1)
+}
+
+/// Implements the `resize_image(path, width, height, op)` Tera function:
+/// reads the source image from `source_dir`, resizes/crops it per `op`, and
+/// writes the result to `processed_images/<hash>.<ext>` under `output_dir`,
+/// skipping the work entirely if that file already exists.
+fn resize_image(
+    args: &HashMap<String, Value>,
+    source_dir: &Path,
+    output_dir: &Path,
+    base_url: &str,
+) -> tera::Result<Value> {
+    let path = tera_required_str(args, "path")?;
+    let width = tera_required_u32(args, "width")?;
+    let height = tera_required_u32(args, "height")?;
+    let op = tera_required_str(args, "op")?;
+
+    let source_path = source_dir.join(path);
+    let ext = source_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("png");
+
+    let mut hasher = DefaultHasher::new();
+    source_path.hash(&mut hasher);
+    width.hash(&mut hasher);
+    height.hash(&mut hasher);
+    op.hash(&mut hasher);
+    let filename = format!("{:016x}.{}", hasher.finish(), ext);
+
+    let processed_dir = output_dir.join("processed_images");
+    let output_path = processed_dir.join(&filename);
+
+    let (final_width, final_height) = if output_path.exists() {
+        let dims = image::image_dimensions(&output_path)
+            .map_err(|e| tera::Error::msg(format!("resize_image: {}", e)))?;
+        dims
+    } else {
+        let img = image::open(&source_path)
+            .map_err(|e| tera::Error::msg(format!("resize_image: failed to open {}: {}", path, e)))?;
+
+        let resized = match op {
+            "scale" => img.resize_exact(width, height, FilterType::Lanczos3),
+            "fit_width" => {
+                let (orig_w, orig_h) = img.dimensions();
+                let target_h = fit_width_height(orig_w, orig_h, width);
+                img.resize_exact(width, target_h, FilterType::Lanczos3)
+            }
+            "fit_height" => {
+                let (orig_w, orig_h) = img.dimensions();
+                let target_w = fit_height_width(orig_w, orig_h, height);
+                img.resize_exact(target_w, height, FilterType::Lanczos3)
+            }
+            "fit" => img.resize(width, height, FilterType::Lanczos3),
+            "crop" => img.resize_to_fill(width, height, FilterType::Lanczos3),
+            other => {
+                return Err(tera::Error::msg(format!(
+                    "resize_image: unknown op `{}`",
+                    other
+                )))
+            }
+        };
+
+        std::fs::create_dir_all(&processed_dir)
+            .map_err(|e| tera::Error::msg(format!("resize_image: {}", e)))?;
+        resized
+            .save(&output_path)
+            .map_err(|e| tera::Error::msg(format!("resize_image: failed to save derivative: {}", e)))?;
+
+        resized.dimensions()
+    };
+
+    let static_path = format!("processed_images/{}", filename);
+    let url = format!("{}/{}", base_url.trim_end_matches('/'), static_path);
+
+    Ok(serde_json::json!({
+        "url": url,
+        "static_path": static_path,
+        "width": final_width,
+        "height": final_height,
+    }))
+}
+
+/// Finds fenced (``` ``` ``` ``` or `~~~`) code spans in raw markdown
+/// source, returning their byte ranges so shortcode expansion can skip
+/// anything inside them.
+fn fenced_code_ranges(source: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut offset = 0usize;
+    let mut in_fence = false;
+    let mut fence_char = '`';
+    let mut fence_len = 0usize;
+    let mut start = 0usize;
+
+    for line in source.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let trimmed = line.trim_end_matches('\n').trim_start();
+
+        if !in_fence {
+            if let Some(ch) = trimmed.chars().next() {
+                if ch == '`' || ch == '~' {
+                    let run = trimmed.chars().take_while(|&c| c == ch).count();
+                    if run >= 3 {
+                        in_fence = true;
+                        fence_char = ch;
+                        fence_len = run;
+                        start = line_start;
+                    }
+                }
+            }
+        } else {
+            let run = trimmed.chars().take_while(|&c| c == fence_char).count();
+            let rest_is_blank = trimmed.chars().skip(run).all(|c| c.is_whitespace());
+            if run >= fence_len && run > 0 && rest_is_blank {
+                in_fence = false;
+                ranges.push((start, offset));
+            }
+        }
+    }
+
+    if in_fence {
+        ranges.push((start, source.len()));
+    }
+
+    ranges
+}
+
+/// Finds indented (4-space/tab) code blocks, i.e. runs of indented lines
+/// that follow a blank line, returning their byte ranges so shortcode
+/// expansion can skip anything inside them.
+fn indented_code_ranges(source: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut offset = 0usize;
+    let mut prev_blank = true;
+    let mut in_block = false;
+    let mut start = 0usize;
+
+    for line in source.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let content = line.trim_end_matches('\n');
+        let is_blank = content.trim().is_empty();
+        let is_indented = content.starts_with("    ") || content.starts_with('\t');
+
+        if !in_block {
+            if is_indented && prev_blank {
+                in_block = true;
+                start = line_start;
+            }
+        } else if !is_indented && !is_blank {
+            in_block = false;
+            ranges.push((start, line_start));
+        }
+
+        prev_blank = is_blank;
+    }
+
+    if in_block {
+        ranges.push((start, source.len()));
+    }
+
+    ranges
+}
+
+/// Splits a BibTeX field list on top-level commas, leaving braces and quoted
+/// strings alone so embedded commas (e.g. in an `author` list) survive.
+fn split_bibtex_fields(s: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0i32;
+    let mut in_quotes = false;
+
+    for ch in s.chars() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            '{' => {
+                depth += 1;
+                current.push(ch);
+            }
+            '}' => {
+                depth -= 1;
+                current.push(ch);
+            }
+            ',' if depth == 0 && !in_quotes => {
+                if !current.trim().is_empty() {
+                    parts.push(current.clone());
+                }
+                current.clear();
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.trim().is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+fn clean_bibtex_value(value: &str) -> String {
+    let value = value.trim();
+    let value = if value.len() >= 2
+        && ((value.starts_with('{') && value.ends_with('}'))
+            || (value.starts_with('"') && value.ends_with('"')))
+    {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    value.trim().to_string()
+}
+
+/// Parses BibTeX source into an array of `{ entry_type, key, fields }`
+/// objects. `@string`/`@comment`/`@preamble` entries are skipped.
+fn parse_bibtex(content: &str) -> Vec<Value> {
+    let chars: Vec<char> = content.chars().collect();
+    let len = chars.len();
+    let mut entries = Vec::new();
+    let mut i = 0;
+
+    while i < len {
+        if chars[i] != '@' {
+            i += 1;
+            continue;
+        }
+
+        i += 1;
+        let type_start = i;
+        while i < len && chars[i] != '{' && chars[i] != '(' {
+            i += 1;
+        }
+        if i >= len {
+            break;
+        }
+        let entry_type = chars[type_start..i].iter().collect::<String>().trim().to_lowercase();
+
+        let open = chars[i];
+        let close = if open == '{' { '}' } else { ')' };
+        i += 1;
+        let body_start = i;
+        let mut depth = 1;
+        while i < len && depth > 0 {
+            if chars[i] == open {
+                depth += 1;
+            } else if chars[i] == close {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            i += 1;
+        }
+        let body: String = chars[body_start..i].iter().collect();
+        i += 1;
+
+        if matches!(entry_type.as_str(), "comment" | "string" | "preamble") {
+            continue;
+        }
+
+        if let Some((key, fields_str)) = body.split_once(',') {
+            let mut fields = serde_json::Map::new();
+            for field in split_bibtex_fields(fields_str) {
+                if let Some((name, value)) = field.split_once('=') {
+                    let name = name.trim().to_lowercase();
+                    if !name.is_empty() {
+                        fields.insert(name, Value::String(clean_bibtex_value(value)));
+                    }
+                }
+            }
+
+            let mut entry = serde_json::Map::new();
+            entry.insert("entry_type".to_string(), Value::String(entry_type));
+            entry.insert("key".to_string(), Value::String(key.trim().to_string()));
+            entry.insert("fields".to_string(), Value::Object(fields));
+            entries.push(Value::Object(entry));
+        }
+    }
+
+    entries
+}
+
+fn parse_csv(content: &str) -> tera::Result<Value> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| tera::Error::msg(format!("load_data: {}", e)))?
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+
+    let mut records = Vec::new();
+    for result in reader.records() {
+        let record = result.map_err(|e| tera::Error::msg(format!("load_data: {}", e)))?;
+        records.push(Value::Array(
+            record.iter().map(|f| Value::String(f.to_string())).collect(),
+        ));
+    }
+
+    Ok(serde_json::json!({ "headers": headers, "records": records }))
+}
+
+/// Implements the `load_data(path, format)` Tera function: reads an
+/// arbitrary file relative to `source_dir` at render time and returns it as
+/// structured data, auto-detecting the format from the extension when
+/// `format` is omitted. Results are cached by absolute path so repeated
+/// calls within one build don't re-read or re-parse the same file.
+fn load_data(
+    args: &HashMap<String, Value>,
+    source_dir: &Path,
+    cache: &Mutex<HashMap<PathBuf, Value>>,
+) -> tera::Result<Value> {
+    let path = tera_required_str(args, "path")?;
+    let absolute_path = source_dir.join(path);
+
+    if let Some(cached) = cache.lock().unwrap().get(&absolute_path) {
+        return Ok(cached.clone());
+    }
+
+    let format = args
+        .get("format")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_lowercase())
+        .or_else(|| {
+            absolute_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|s| s.to_lowercase())
+        })
+        .ok_or_else(|| tera::Error::msg("load_data: could not determine format from extension"))?;
+
+    let raw = std::fs::read_to_string(&absolute_path)
+        .map_err(|e| tera::Error::msg(format!("load_data: failed to read {}: {}", path, e)))?;
+
+    let value = match format.as_str() {
+        "json" => serde_json::from_str(&raw).map_err(|e| tera::Error::msg(e.to_string()))?,
+        "yaml" | "yml" => {
+            let parsed: serde_yaml::Value =
+                serde_yaml::from_str(&raw).map_err(|e| tera::Error::msg(e.to_string()))?;
+            serde_json::to_value(parsed).map_err(|e| tera::Error::msg(e.to_string()))?
+        }
+        "toml" => {
+            let parsed: toml::Value =
+                toml::from_str(&raw).map_err(|e| tera::Error::msg(e.to_string()))?;
+            serde_json::to_value(parsed).map_err(|e| tera::Error::msg(e.to_string()))?
+        }
+        "csv" => parse_csv(&raw)?,
+        "bibtex" | "bib" => Value::Array(parse_bibtex(&raw)),
+        other => return Err(tera::Error::msg(format!("load_data: unsupported format `{}`", other))),
+    };
+
+    cache.lock().unwrap().insert(absolute_path, value.clone());
+    Ok(value)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Implements the `get_file_hash(sha="256", base64=true)` Tera filter: reads
+/// the piped-in path relative to `root_dir` and returns a SHA-256/384/512
+/// digest, base64-encoded by default, for use in `integrity="sha384-..."`
+/// attributes.
+fn get_file_hash(value: &Value, args: &HashMap<String, Value>, root_dir: &Path) -> tera::Result<Value> {
+    let path = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("get_file_hash: expected a string path"))?;
+    let sha = args.get("sha").and_then(|v| v.as_str()).unwrap_or("256");
+    let want_base64 = args.get("base64").and_then(|v| v.as_bool()).unwrap_or(true);
+
+    let full_path = root_dir.join(path.trim_start_matches('/'));
+    let bytes = std::fs::read(&full_path)
+        .map_err(|e| tera::Error::msg(format!("get_file_hash: failed to read {}: {}", path, e)))?;
+
+    let digest: Vec<u8> = match sha {
+        "256" => Sha256::digest(&bytes).to_vec(),
+        "384" => Sha384::digest(&bytes).to_vec(),
+        "512" => Sha512::digest(&bytes).to_vec(),
+        other => return Err(tera::Error::msg(format!("get_file_hash: unsupported sha `{}`", other))),
+    };
+
+    let encoded = if want_base64 {
+        base64::encode(&digest)
+    } else {
+        to_hex(&digest)
+    };
+
+    Ok(Value::String(encoded))
+}
+
 pub struct TemplateEngine {
     tera: Tera,
     data: HashMap<String, Value>,
+    minify_html: bool,
 }
 
 impl TemplateEngine {
-    pub fn new(source_dir: &Path) -> Result<Self> {
+    pub fn new(
+        source_dir: &Path,
+        output_dir: &Path,
+        base_url: &str,
+        minify_html: bool,
+    ) -> Result<Self> {
         let mut tera = Tera::new("templates/**/*.html").unwrap_or_else(|e| {
             println!("Failed to load from templates/: {}", e);
             Tera::default()
         });
-        
+
         let data = Self::load_data_files(source_dir)?;
-        
+
+        let image_source_dir = source_dir.to_path_buf();
+        let image_output_dir = output_dir.to_path_buf();
+        let image_base_url = base_url.to_string();
+        tera.register_function(
+            "resize_image",
+            move |args: &HashMap<String, Value>| -> tera::Result<Value> {
+                resize_image(args, &image_source_dir, &image_output_dir, &image_base_url)
+            },
+        );
+
+        let load_data_source_dir = source_dir.to_path_buf();
+        let load_data_cache: Arc<Mutex<HashMap<PathBuf, Value>>> = Arc::new(Mutex::new(HashMap::new()));
+        tera.register_function(
+            "load_data",
+            move |args: &HashMap<String, Value>| -> tera::Result<Value> {
+                load_data(args, &load_data_source_dir, &load_data_cache)
+            },
+        );
+
         tera.register_filter("escape", |value: &Value, _: &HashMap<String, Value>| {
             match value {
                 Value::String(s) => Ok(Value::String(html_escape::encode_text(s).to_string())),
@@ -48,14 +562,42 @@ impl TemplateEngine {
                 let site_url = args.get("site_url")
                     .and_then(|v| v.as_str())
                     .unwrap_or("");
-                
+
                 Ok(Value::String(format!("{}{}", site_url, url)))
             } else {
                 Ok(value.clone())
             }
         });
 
-        Ok(TemplateEngine { tera, data })
+        let hash_root_dir = output_dir.to_path_buf();
+        tera.register_filter(
+            "get_file_hash",
+            move |value: &Value, args: &HashMap<String, Value>| -> tera::Result<Value> {
+                get_file_hash(value, args, &hash_root_dir)
+            },
+        );
+
+        Ok(TemplateEngine {
+            tera,
+            data,
+            minify_html,
+        })
+    }
+
+    /// Minifies `html` when `minify_html` is enabled in the site config;
+    /// otherwise returns it unchanged so dev builds stay debuggable.
+    fn maybe_minify(&self, html: String) -> String {
+        if !self.minify_html {
+            return html;
+        }
+
+        let mut cfg = minify_html::Cfg::new();
+        cfg.minify_js = true;
+        cfg.minify_css = true;
+        match String::from_utf8(minify_html::minify(html.as_bytes(), &cfg)) {
+            Ok(minified) => minified,
+            Err(_) => html,
+        }
     }
 
     fn load_data_files(source_dir: &Path) -> Result<HashMap<String, Value>> {
@@ -90,10 +632,19 @@ impl TemplateEngine {
         Ok(data)
     }
 
-    pub fn render_content(&self, content: &ContentFile, site_config: &crate::config::SiteConfig) -> Result<String> {
+    pub fn render_content(
+        &self,
+        content: &ContentFile,
+        all_content: &[ContentFile],
+        site_config: &crate::config::SiteConfig,
+    ) -> Result<String> {
         let mut context = Context::new();
-        
-        context.insert("page", &content.front_matter);
+
+        let mut page = serde_json::to_value(&content.front_matter)?;
+        if let Value::Object(ref mut page) = page {
+            page.insert("toc".to_string(), serde_json::to_value(&content.toc)?);
+        }
+        context.insert("page", &page);
         context.insert("content", &content.html_content);
         context.insert("site", site_config);
         context.insert("data", &self.data);
@@ -107,7 +658,8 @@ impl TemplateEngine {
             }
         }
 
-        let language_urls = content.get_language_urls();
+        let language_urls =
+            content.get_language_urls(all_content, &site_config.default_language);
         context.insert("language_urls", &language_urls);
 
         let layout = content.front_matter.layout
@@ -116,13 +668,201 @@ impl TemplateEngine {
             .clone();
 
         let template_name = format!("{}.html", layout);
-        
-        self.tera.render(&template_name, &context)
-            .map_err(|e| anyhow::anyhow!("Template rendering error: {}", e))
+
+        let rendered = self.tera.render(&template_name, &context)
+            .map_err(|e| anyhow::anyhow!("Template rendering error: {}", e))?;
+        Ok(self.maybe_minify(rendered))
     }
 
     pub fn render_page(&self, template_name: &str, context: &Context) -> Result<String> {
-        self.tera.render(template_name, context)
-            .map_err(|e| anyhow::anyhow!("Template rendering error: {}", e))
+        let rendered = self.tera.render(template_name, context)
+            .map_err(|e| anyhow::anyhow!("Template rendering error: {}", e))?;
+        Ok(self.maybe_minify(rendered))
+    }
+
+    /// Expands `{{ name(arg="val") }}` and `{% name(arg="val") %}body{% end %}`
+    /// shortcodes in raw markdown source by rendering `shortcodes/<name>.html`
+    /// through this engine's `Tera` instance. `ContentFile::from_path` runs
+    /// this on a file's body before converting it to HTML. Matches inside fenced
+    /// or indented code spans are left untouched so documentation of the
+    /// shortcode syntax itself isn't expanded. Unknown shortcode names are
+    /// also left untouched rather than erroring.
+    pub fn expand_shortcodes(&self, source: &str) -> Result<String> {
+        let mut output = String::new();
+        let mut last_end = 0;
+        let mut counts: HashMap<String, u32> = HashMap::new();
+
+        let mut code_ranges = fenced_code_ranges(source);
+        code_ranges.extend(indented_code_ranges(source));
+
+        for caps in SHORTCODE_RE.captures_iter(source) {
+            let whole = caps.get(0).unwrap();
+            output.push_str(&source[last_end..whole.start()]);
+            last_end = whole.end();
+
+            if code_ranges
+                .iter()
+                .any(|&(start, end)| whole.start() >= start && whole.start() < end)
+            {
+                output.push_str(whole.as_str());
+                continue;
+            }
+
+            let (name, args, body) = if let Some(bname) = caps.name("bname") {
+                (
+                    bname.as_str(),
+                    caps.name("bargs").map_or("", |m| m.as_str()),
+                    caps.name("bbody").map(|m| m.as_str()),
+                )
+            } else {
+                (
+                    caps.name("iname").unwrap().as_str(),
+                    caps.name("iargs").map_or("", |m| m.as_str()),
+                    None,
+                )
+            };
+
+            let template_name = format!("shortcodes/{}.html", name);
+            if !self.tera.get_template_names().any(|t| t == template_name) {
+                output.push_str(whole.as_str());
+                continue;
+            }
+
+            let nth = counts.entry(name.to_string()).or_insert(0);
+            *nth += 1;
+
+            let mut context = Context::new();
+            for (key, value) in parse_shortcode_args(args) {
+                context.insert(&key, &value);
+            }
+            context.insert("nth", nth);
+            if let Some(body) = body {
+                context.insert("body", body);
+            }
+
+            let rendered = self
+                .tera
+                .render(&template_name, &context)
+                .map_err(|e| anyhow::anyhow!("Shortcode rendering error: {}", e))?;
+            output.push_str(&rendered);
+        }
+
+        output.push_str(&source[last_end..]);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_width_height_preserves_aspect_ratio() {
+        // 1000x500 scaled to width 200 keeps the 2:1 ratio -> height 100.
+        assert_eq!(fit_width_height(1000, 500, 200), 100);
+    }
+
+    #[test]
+    fn fit_width_height_rounds_and_clamps_to_one_pixel() {
+        // Rounds rather than truncates.
+        assert_eq!(fit_width_height(3, 2, 4), 3);
+        // Never returns 0 even for a wildly non-matching target width.
+        assert_eq!(fit_width_height(10000, 1, 1), 1);
+    }
+
+    #[test]
+    fn fit_height_width_preserves_aspect_ratio() {
+        // 1000x500 scaled to height 100 keeps the 2:1 ratio -> width 200.
+        assert_eq!(fit_height_width(1000, 500, 100), 200);
+    }
+
+    #[test]
+    fn fit_height_width_rounds_and_clamps_to_one_pixel() {
+        assert_eq!(fit_height_width(2, 3, 4), 3);
+        assert_eq!(fit_height_width(1, 10000, 1), 1);
+    }
+
+    #[test]
+    fn clean_bibtex_value_strips_braces_and_quotes() {
+        assert_eq!(clean_bibtex_value("{Some Title}"), "Some Title");
+        assert_eq!(clean_bibtex_value("\"Some Title\""), "Some Title");
+        assert_eq!(clean_bibtex_value("2024"), "2024");
+    }
+
+    #[test]
+    fn clean_bibtex_value_leaves_single_char_values_alone() {
+        // Previously panicked by slicing `value[1..value.len() - 1]`
+        // without checking the value was long enough to have matching
+        // delimiters to strip.
+        assert_eq!(clean_bibtex_value("{"), "{");
+        assert_eq!(clean_bibtex_value("\""), "\"");
+        assert_eq!(clean_bibtex_value(""), "");
+    }
+
+    #[test]
+    fn parse_bibtex_extracts_entry_type_key_and_fields() {
+        let source = r#"@article{doe2024, title = {A Great Paper}, author = "Jane Doe", year = 2024}"#;
+        let entries = parse_bibtex(source);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry["entry_type"], "article");
+        assert_eq!(entry["key"], "doe2024");
+        assert_eq!(entry["fields"]["title"], "A Great Paper");
+        assert_eq!(entry["fields"]["author"], "Jane Doe");
+        assert_eq!(entry["fields"]["year"], "2024");
+    }
+
+    #[test]
+    fn parse_bibtex_skips_string_comment_and_preamble_entries() {
+        let source = r#"
+            @string{anthropic = "Anthropic"}
+            @comment{this is ignored}
+            @preamble{"some preamble"}
+            @book{real2024, title = {Real Entry}}
+        "#;
+        let entries = parse_bibtex(source);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["key"], "real2024");
+    }
+
+    #[test]
+    fn parse_bibtex_keeps_commas_inside_braces_and_quotes_together() {
+        let source = r#"@article{multi2024, author = {Doe, Jane and Roe, Richard}, note = "a, b, c"}"#;
+        let entries = parse_bibtex(source);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["fields"]["author"], "Doe, Jane and Roe, Richard");
+        assert_eq!(entries[0]["fields"]["note"], "a, b, c");
+    }
+
+    #[test]
+    fn parse_shortcode_args_parses_strings_numbers_and_bools() {
+        let args = parse_shortcode_args(r#"title="Hello, world", count=3, ratio=1.5, active=true"#);
+        assert_eq!(
+            args,
+            vec![
+                ("title".to_string(), Value::String("Hello, world".to_string())),
+                ("count".to_string(), serde_json::json!(3)),
+                ("ratio".to_string(), serde_json::json!(1.5)),
+                ("active".to_string(), Value::Bool(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_shortcode_args_treats_bare_words_as_strings() {
+        let args = parse_shortcode_args("kind=featured");
+        assert_eq!(
+            args,
+            vec![("kind".to_string(), Value::String("featured".to_string()))]
+        );
+    }
+
+    #[test]
+    fn parse_shortcode_args_ignores_parts_without_an_equals_sign() {
+        let args = parse_shortcode_args("title=\"Hi\", standalone");
+        assert_eq!(
+            args,
+            vec![("title".to_string(), Value::String("Hi".to_string()))]
+        );
     }
 }
\ No newline at end of file